@@ -7,13 +7,32 @@
         unused_import_braces, unused_qualifications)]
 
 extern crate byteorder;
+#[cfg(feature = "compression")]
+extern crate flate2;
 extern crate rustc_serialize;
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "compression")]
+use flate2::read::{GzDecoder, ZlibDecoder};
+use rustc_serialize::json;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
-use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+#[cfg(feature = "compression")]
+use std::io::BufRead;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+/// The number of recently materialized shots that a `Reader` keeps around without re-reading.
+const DEFAULT_CACHE_CAPACITY: usize = 16;
+
+/// The byte length of the smallest possible segment (zero samples): sample count, time
+/// interval, and the reserved word.
+const MIN_SEGMENT_LEN: u64 = 6;
+
+/// The number of leading samples averaged by `Segment::baseline`.
+const BASELINE_SAMPLE_COUNT: usize = 10;
+
 /// Our custom error enum.
 #[derive(Debug)]
 pub enum Error {
@@ -23,6 +42,10 @@ pub enum Error {
     InvalidShotNumber(u16),
     /// Wrapper around `std::io::Error`.
     Io(io::Error),
+    /// Wrapper around an error encoding an `Index` as json.
+    EncodeIndex(json::EncoderError),
+    /// Wrapper around an error decoding an `Index` from json.
+    DecodeIndex(json::DecoderError),
 }
 
 impl From<io::Error> for Error {
@@ -31,6 +54,18 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<json::EncoderError> for Error {
+    fn from(err: json::EncoderError) -> Error {
+        Error::EncodeIndex(err)
+    }
+}
+
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Error {
+        Error::DecodeIndex(err)
+    }
+}
+
 /// Our custom result type.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -38,8 +73,11 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub struct Reader<R: Read> {
     reader: R,
+    index: Option<Index>,
+    cache: ShotCache,
 }
 
+#[cfg(not(feature = "compression"))]
 impl Reader<BufReader<File>> {
     /// Opens a reader for the file at a path.
     ///
@@ -51,7 +89,107 @@ impl Reader<BufReader<File>> {
     /// ```
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader<BufReader<File>>> {
         let file = BufReader::new(File::open(path)?);
-        Ok(Reader { reader: file })
+        Ok(Reader {
+            reader: file,
+            index: None,
+            cache: ShotCache::new(DEFAULT_CACHE_CAPACITY),
+        })
+    }
+}
+
+/// The byte source backing a `Reader`, transparently unwrapping gzip- or zlib-compressed df2
+/// files.
+///
+/// Compressed input is fully decompressed into memory up front, so `Source` stays `Seek` and
+/// the feature is purely additive: `seek`, `build_index`, `with_index`, and `shot` keep working
+/// exactly as they do for a plain `BufReader<File>`. Only present when the `compression` feature
+/// is enabled, so the default build keeps zero extra dependencies.
+#[cfg(feature = "compression")]
+#[derive(Debug)]
+pub enum Source {
+    /// An uncompressed df2 file.
+    Plain(BufReader<File>),
+    /// The fully decompressed bytes of a gzip- or zlib-wrapped df2 file.
+    Decompressed(io::Cursor<Vec<u8>>),
+}
+
+#[cfg(feature = "compression")]
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Source::Plain(ref mut read) => read.read(buf),
+            Source::Decompressed(ref mut read) => read.read(buf),
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Seek for Source {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            Source::Plain(ref mut seek) => seek.seek(pos),
+            Source::Decompressed(ref mut seek) => seek.seek(pos),
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Reader<Source> {
+    /// Opens a reader for the file at a path, transparently decompressing gzip or zlib framing.
+    ///
+    /// The first two bytes of the file are sniffed to detect the gzip magic number (`1f 8b`) or
+    /// a zlib header; anything else is read as plain df2 bytes. Compressed input is decompressed
+    /// into memory up front, so the resulting reader supports `seek` and friends just like an
+    /// uncompressed one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Reader;
+    /// let reader = Reader::from_path("data/one-shot.df2").unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader<Source>> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 2];
+        let available = {
+            let buffer = file.fill_buf()?;
+            let available = cmp::min(2, buffer.len());
+            magic[..available].copy_from_slice(&buffer[..available]);
+            available
+        };
+        let source = if available == 2 && magic == [0x1f, 0x8b] {
+            let mut decoder = GzDecoder::new(file)?;
+            let mut decompressed = Vec::new();
+            decoder.read_to_end(&mut decompressed)?;
+            Source::Decompressed(io::Cursor::new(decompressed))
+        } else if available == 2 && looks_like_zlib(magic[0], magic[1]) {
+            let mut decoder = ZlibDecoder::new(file);
+            let mut decompressed = Vec::new();
+            decoder.read_to_end(&mut decompressed)?;
+            Source::Decompressed(io::Cursor::new(decompressed))
+        } else {
+            Source::Plain(file)
+        };
+        Ok(Reader {
+            reader: source,
+            index: None,
+            cache: ShotCache::new(DEFAULT_CACHE_CAPACITY),
+        })
+    }
+}
+
+#[cfg(feature = "compression")]
+fn looks_like_zlib(cmf: u8, flg: u8) -> bool {
+    cmf & 0x0f == 8 && (cmf as u16 * 256 + flg as u16) % 31 == 0
+}
+
+/// Turns an EOF caused by running past a shot's bounded reader into `Error::InvalidOffset`.
+///
+/// Any other error (including a non-EOF `io::Error`) passes through unchanged.
+fn overrun_to_invalid_offset<F: Fn() -> Error>(err: Error, invalid_offset: F) -> Error {
+    match err {
+        Error::Io(ref io_err) if io_err.kind() == ErrorKind::UnexpectedEof => invalid_offset(),
+        _ => err,
     }
 }
 
@@ -66,7 +204,6 @@ impl<R: Read> Reader<R> {
     /// let shot = reader.read_one().unwrap().unwrap();
     /// ```
     pub fn read_one(&mut self) -> Result<Option<Shot>> {
-        // FIXME this isn't exactly correct, a spare byte could be allowed
         let number = match self.reader.read_u16::<LittleEndian>() {
             Ok(number) => number,
             Err(err) => {
@@ -76,27 +213,104 @@ impl<R: Read> Reader<R> {
                 }
             }
         };
+        self.read_shot_body(number).map(Some)
+    }
+
+    /// Reads the offset word, outgoing pulse, and segments for a shot whose number has already
+    /// been read.
+    ///
+    /// The outgoing pulse and segments are parsed through a reader bounded to `offset * 2`
+    /// bytes, so a malformed sample count or time interval cannot read past the shot boundary:
+    /// running past the bound surfaces as `Error::InvalidOffset`, the same error a too-small
+    /// leftover produces, so `Recovering` can resynchronize from either kind of corruption.
+    fn read_shot_body(&mut self, number: u16) -> Result<Shot> {
         let offset = self.reader.read_u16::<LittleEndian>()?;
-        let mut bytes_remaining = offset * 2;
-        let outgoing = Segment::from_read(&mut self.reader)?;
-        bytes_remaining -= outgoing.len();
+        let mut take = (&mut self.reader).take(offset as u64 * 2);
+        let invalid_offset = || {
+            Error::InvalidOffset {
+                shot_number: number,
+                offset: offset,
+            }
+        };
+        let outgoing = Segment::from_read(&mut take)
+            .map_err(|err| overrun_to_invalid_offset(err, invalid_offset))?;
         let mut segments = Vec::new();
-        while bytes_remaining > 0 {
-            let segment = Segment::from_read(&mut self.reader)?;
-            if segment.len() > bytes_remaining {
-                return Err(Error::InvalidOffset {
-                    shot_number: number,
-                    offset: offset,
-                });
+        loop {
+            let remaining = take.limit();
+            if remaining == 0 {
+                break;
+            }
+            if remaining < MIN_SEGMENT_LEN {
+                return Err(invalid_offset());
             }
-            bytes_remaining -= segment.len();
+            let segment = Segment::from_read(&mut take)
+                .map_err(|err| overrun_to_invalid_offset(err, invalid_offset))?;
             segments.push(segment);
         }
-        Ok(Some(Shot {
+        let shot = Shot {
             number: number,
             outgoing: outgoing.data,
             segments: segments,
-        }))
+        };
+        self.cache.insert(shot.clone());
+        Ok(shot)
+    }
+
+    /// Resynchronizes to the next occurrence of `expected_number` encoded as a little-endian
+    /// shot number, consuming bytes up to and including it.
+    ///
+    /// Returns `Ok(false)` if the stream ends before `expected_number` is found.
+    fn resync(&mut self, expected_number: u16) -> Result<bool> {
+        let target = [(expected_number & 0xff) as u8, (expected_number >> 8) as u8];
+        let mut window = [0u8; 2];
+        if let Err(err) = self.reader.read_exact(&mut window) {
+            return match err.kind() {
+                ErrorKind::UnexpectedEof => Ok(false),
+                _ => Err(err.into()),
+            };
+        }
+        loop {
+            if window == target {
+                return Ok(true);
+            }
+            let mut next_byte = [0u8; 1];
+            if let Err(err) = self.reader.read_exact(&mut next_byte) {
+                return match err.kind() {
+                    ErrorKind::UnexpectedEof => Ok(false),
+                    _ => Err(err.into()),
+                };
+            }
+            window[0] = window[1];
+            window[1] = next_byte[0];
+        }
+    }
+
+    /// Wraps this reader in an iterator that, on a corrupt offset, resynchronizes to the next
+    /// plausible shot header instead of aborting the whole stream.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Reader;
+    /// let shots: Vec<_> = Reader::from_path("data/one-shot.df2").unwrap().recovering().collect();
+    /// ```
+    pub fn recovering(self) -> Recovering<R> {
+        Recovering { reader: self }
+    }
+
+    /// Attaches an index to this reader, so that `seek` can consult it instead of scanning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Reader;
+    /// let mut reader = Reader::from_path("data/two-shots.df2").unwrap();
+    /// let index = reader.build_index().unwrap();
+    /// let reader = reader.with_index(index);
+    /// ```
+    pub fn with_index(mut self, index: Index) -> Reader<R> {
+        self.index = Some(index);
+        self
     }
 }
 
@@ -120,7 +334,10 @@ impl<R: Read + Seek> Reader<R> {
         if number == 0 {
             return Err(Error::InvalidShotNumber(number));
         }
-        // TODO optimize by saving locations?
+        if let Some(position) = self.index.as_ref().and_then(|index| index.position(number)) {
+            self.reader.seek(SeekFrom::Start(position))?;
+            return Ok(());
+        }
         self.reader.seek(SeekFrom::Start(2))?;
         let mut position: u64 = 2;
         let mut current = 1;
@@ -137,6 +354,53 @@ impl<R: Read + Seek> Reader<R> {
         self.reader.seek(SeekFrom::Current(-2))?;
         Ok(())
     }
+
+    /// Scans the whole file once, recording the byte position of every shot by shot number.
+    ///
+    /// The reader's position is left where it was before the scan.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Reader;
+    /// let mut reader = Reader::from_path("data/two-shots.df2").unwrap();
+    /// let index = reader.build_index().unwrap();
+    /// assert!(index.position(1).is_some());
+    /// ```
+    pub fn build_index(&mut self) -> Result<Index> {
+        let start = self.reader.seek(SeekFrom::Current(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut positions = HashMap::new();
+        loop {
+            let position = self.reader.seek(SeekFrom::Current(0))?;
+            match self.read_one()? {
+                Some(shot) => {
+                    positions.insert(shot.number, position);
+                }
+                None => break,
+            }
+        }
+        self.reader.seek(SeekFrom::Start(start))?;
+        Ok(Index { positions: positions })
+    }
+
+    /// Returns the shot with the given number, preferring the bounded cache to a fresh seek.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Reader;
+    /// let mut reader = Reader::from_path("data/two-shots.df2").unwrap();
+    /// let shot = reader.shot(2).unwrap();
+    /// assert_eq!(2, shot.number);
+    /// ```
+    pub fn shot(&mut self, number: u16) -> Result<Shot> {
+        if let Some(shot) = self.cache.get(number) {
+            return Ok(shot);
+        }
+        self.seek(number)?;
+        self.read_one()?.ok_or(Error::InvalidShotNumber(number))
+    }
 }
 
 impl<R: Read> Iterator for Reader<R> {
@@ -154,8 +418,188 @@ impl<R: Read> Iterator for Reader<R> {
     }
 }
 
+/// An iterator, created by `Reader::recovering`, that resynchronizes to the next plausible shot
+/// header instead of aborting the whole stream when it hits a corrupt offset.
+#[derive(Debug)]
+pub struct Recovering<R: Read> {
+    reader: Reader<R>,
+}
+
+impl<R: Read> Iterator for Recovering<R> {
+    type Item = Result<Shot>;
+    fn next(&mut self) -> Option<Result<Shot>> {
+        match self.reader.read_one() {
+            Ok(Some(shot)) => Some(Ok(shot)),
+            Ok(None) => None,
+            Err(Error::InvalidOffset { shot_number, .. }) => {
+                let expected = shot_number.wrapping_add(1);
+                match self.reader.resync(expected) {
+                    Ok(true) => Some(self.reader.read_shot_body(expected)),
+                    Ok(false) => None,
+                    Err(err) => Some(Err(err)),
+                }
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Writes df2 waveform data.
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    writer: W,
+}
+
+impl Writer<BufWriter<File>> {
+    /// Creates a writer for the file at a path.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use df2::Writer;
+    /// let writer = Writer::create("data/one-shot.df2").unwrap();
+    /// ```
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Writer<BufWriter<File>>> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Writer { writer: file })
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new writer that wraps the given `Write`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Writer;
+    /// let writer = Writer::new(Vec::new());
+    /// ```
+    pub fn new(writer: W) -> Writer<W> {
+        Writer { writer: writer }
+    }
+
+    /// Writes one shot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::{Shot, Writer};
+    /// let shot = Shot { number: 1, outgoing: Vec::new(), segments: Vec::new() };
+    /// let mut writer = Writer::new(Vec::new());
+    /// writer.write_one(&shot).unwrap();
+    /// ```
+    pub fn write_one(&mut self, shot: &Shot) -> Result<()> {
+        shot.write_to(&mut self.writer)
+    }
+}
+
+/// A random-access index of shot byte positions, built from a single scan of a df2 file.
+///
+/// Holding onto an `Index` turns `Reader::seek` into a single lookup instead of a linear scan,
+/// and the index can be saved alongside the df2 file so that repeated queries (e.g. the CLI
+/// `shot` subcommand invoked many times) don't have to rebuild it.
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Index {
+    positions: HashMap<u16, u64>,
+}
+
+impl Index {
+    /// Returns the byte position of the shot with the given number, if it is in this index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Reader;
+    /// let mut reader = Reader::from_path("data/one-shot.df2").unwrap();
+    /// let index = reader.build_index().unwrap();
+    /// assert_eq!(None, index.position(2));
+    /// ```
+    pub fn position(&self, number: u16) -> Option<u64> {
+        self.positions.get(&number).cloned()
+    }
+
+    /// Loads an index from a sidecar file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use df2::Index;
+    /// let index = Index::load("data/one-shot.df2.index.json").unwrap();
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Index> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        Ok(json::decode(&contents)?)
+    }
+
+    /// Saves this index to a sidecar file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use df2::Reader;
+    /// let mut reader = Reader::from_path("data/one-shot.df2").unwrap();
+    /// let index = reader.build_index().unwrap();
+    /// index.save("data/one-shot.df2.index.json").unwrap();
+    /// ```
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let encoded = json::encode(self)?;
+        File::create(path)?.write_all(encoded.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A bounded, least-recently-used cache of recently materialized shots.
+///
+/// Keeping a handful of shots around makes repeated queries (e.g. `Reader::shot` asked again for
+/// a shot that was just read) cheap without re-parsing from the underlying reader.
+#[derive(Debug)]
+struct ShotCache {
+    capacity: usize,
+    order: VecDeque<u16>,
+    shots: HashMap<u16, Shot>,
+}
+
+impl ShotCache {
+    fn new(capacity: usize) -> ShotCache {
+        ShotCache {
+            capacity: capacity,
+            order: VecDeque::new(),
+            shots: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, number: u16) -> Option<Shot> {
+        match self.shots.get(&number).cloned() {
+            Some(shot) => {
+                self.touch(number);
+                Some(shot)
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, shot: Shot) {
+        let number = shot.number;
+        if !self.shots.contains_key(&number) && self.shots.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.shots.remove(&oldest);
+            }
+        }
+        self.shots.insert(number, shot);
+        self.touch(number);
+    }
+
+    fn touch(&mut self, number: u16) {
+        if let Some(position) = self.order.iter().position(|&n| n == number) {
+            self.order.remove(position);
+        }
+        self.order.push_back(number);
+    }
+}
+
 /// A laser shot.
-#[derive(Debug, PartialEq, RustcEncodable)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable)]
 pub struct Shot {
     /// The shot number (one-indexed).
     pub number: u16,
@@ -165,8 +609,59 @@ pub struct Shot {
     pub segments: Vec<Segment>,
 }
 
+impl Shot {
+    /// Writes this shot to a `Write`, in the same layout `Reader::read_one` expects.
+    ///
+    /// The offset word is recomputed from the outgoing pulse and segment lengths, so a
+    /// hand-built `Shot` does not need to track it itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Shot;
+    /// let shot = Shot { number: 1, outgoing: Vec::new(), segments: Vec::new() };
+    /// let mut bytes = Vec::new();
+    /// shot.write_to(&mut bytes).unwrap();
+    /// ```
+    pub fn write_to<W: Write>(&self, write: &mut W) -> Result<()> {
+        write.write_u16::<LittleEndian>(self.number)?;
+        let outgoing = Segment {
+            data: self.outgoing.clone(),
+            time_interval: 0,
+        };
+        let mut total_bytes = outgoing.len();
+        for segment in &self.segments {
+            total_bytes += segment.len();
+        }
+        write.write_u16::<LittleEndian>(total_bytes / 2)?;
+        outgoing.write_to(write)?;
+        for segment in &self.segments {
+            segment.write_to(write)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the total number of samples in this shot, across the outgoing pulse and every
+    /// waveform segment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use df2::Shot;
+    /// let shot = Shot { number: 1, outgoing: vec![1, 2], segments: Vec::new() };
+    /// assert_eq!(2, shot.total_samples());
+    /// ```
+    pub fn total_samples(&self) -> usize {
+        let mut total = self.outgoing.len();
+        for segment in &self.segments {
+            total += segment.data.len();
+        }
+        total
+    }
+}
+
 /// A waveform segment.
-#[derive(Debug, PartialEq, RustcEncodable)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable)]
 pub struct Segment {
     /// The waveform samples.
     pub data: Vec<u16>,
@@ -212,6 +707,27 @@ impl Segment {
         })
     }
 
+    /// Writes this segment to a `Write`, in the same layout `Segment::from_read` expects.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use df2::Segment;
+    /// let segment = Segment { data: vec![1, 2, 3], time_interval: 4 };
+    /// let mut bytes = Vec::new();
+    /// segment.write_to(&mut bytes).unwrap();
+    /// ```
+    pub fn write_to<W: Write>(&self, write: &mut W) -> Result<()> {
+        write.write_u16::<LittleEndian>(self.data.len() as u16)?;
+        for sample in &self.data {
+            write.write_u16::<LittleEndian>(*sample)?;
+        }
+        write.write_u16::<LittleEndian>(self.time_interval)?;
+        // reserved
+        write.write_u16::<LittleEndian>(0)?;
+        Ok(())
+    }
+
     /// Returns the length of this segment in bytes.
     ///
     /// # Examples
@@ -239,11 +755,74 @@ impl Segment {
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
+
+    /// Returns the index and amplitude of this segment's largest sample.
+    ///
+    /// Returns `None` if the segment has no samples.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use df2::Segment;
+    /// let segment = Segment { data: vec![1, 5, 3], time_interval: 0 };
+    /// assert_eq!(Some((1, 5)), segment.peak());
+    /// ```
+    pub fn peak(&self) -> Option<(usize, u16)> {
+        self.data
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, &sample)| sample)
+            .map(|(index, &sample)| (index, sample))
+    }
+
+    /// Returns the mean of this segment's leading samples, used to estimate the noise floor
+    /// before the waveform rises.
+    ///
+    /// Returns `None` if the segment has no samples.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use df2::Segment;
+    /// let segment = Segment { data: vec![2, 4], time_interval: 0 };
+    /// assert_eq!(Some(3.0), segment.baseline());
+    /// ```
+    pub fn baseline(&self) -> Option<f64> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let count = cmp::min(BASELINE_SAMPLE_COUNT, self.data.len());
+        let sum: u64 = self.data[..count].iter().map(|&sample| sample as u64).sum();
+        Some(sum as f64 / count as f64)
+    }
 }
 
+#[cfg(test)]
+extern crate quickcheck;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use quickcheck::{quickcheck, Arbitrary, Gen};
+
+    impl Arbitrary for Segment {
+        fn arbitrary<G: Gen>(g: &mut G) -> Segment {
+            Segment {
+                data: Arbitrary::arbitrary(g),
+                time_interval: Arbitrary::arbitrary(g),
+            }
+        }
+    }
+
+    impl Arbitrary for Shot {
+        fn arbitrary<G: Gen>(g: &mut G) -> Shot {
+            Shot {
+                number: Arbitrary::arbitrary(g),
+                outgoing: Arbitrary::arbitrary(g),
+                segments: Arbitrary::arbitrary(g),
+            }
+        }
+    }
 
     #[test]
     fn reader_from_path() {
@@ -290,4 +869,184 @@ mod tests {
         let segment = Segment::from_path("data/one-segment.bin").unwrap();
         assert_eq!(110, segment.len());
     }
+
+    #[test]
+    fn segment_write_then_read() {
+        let segment = Segment {
+            data: vec![1, 2, 3, 4, 5],
+            time_interval: 42,
+        };
+        let mut bytes = Vec::new();
+        segment.write_to(&mut bytes).unwrap();
+        let round_tripped = Segment::from_read(&mut &bytes[..]).unwrap();
+        assert_eq!(segment, round_tripped);
+    }
+
+    #[test]
+    fn shot_write_then_read_round_trips() {
+        fn prop(shot: Shot) -> bool {
+            let mut bytes = Vec::new();
+            shot.write_to(&mut bytes).unwrap();
+            let mut reader = Reader {
+                reader: &bytes[..],
+                index: None,
+                cache: ShotCache::new(DEFAULT_CACHE_CAPACITY),
+            };
+            reader.read_one().unwrap().unwrap() == shot
+        }
+        quickcheck(prop as fn(Shot) -> bool);
+    }
+
+    #[test]
+    fn reader_build_index() {
+        let mut reader = Reader::from_path("data/four-shots.df2").unwrap();
+        let index = reader.build_index().unwrap();
+        for number in 1..5 {
+            assert!(index.position(number).is_some());
+        }
+        assert_eq!(None, index.position(5));
+    }
+
+    #[test]
+    fn reader_seek_with_index() {
+        let mut reader = Reader::from_path("data/four-shots.df2").unwrap();
+        let index = reader.build_index().unwrap();
+        let mut reader = reader.with_index(index);
+        reader.seek(3).unwrap();
+        let shot = reader.read_one().unwrap().unwrap();
+        assert_eq!(3, shot.number);
+    }
+
+    #[test]
+    fn index_save_and_load_round_trips() {
+        let mut reader = Reader::from_path("data/four-shots.df2").unwrap();
+        let index = reader.build_index().unwrap();
+        let path = std::env::temp_dir().join("df2-index-save-and-load-round-trips.json");
+        index.save(&path).unwrap();
+        let loaded = Index::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(index, loaded);
+    }
+
+    #[test]
+    fn reader_shot_uses_cache() {
+        let mut reader = Reader::from_path("data/four-shots.df2").unwrap();
+        let first = reader.shot(2).unwrap();
+        let second = reader.shot(2).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn reader_from_path_sniffs_gzip() {
+        let mut reader = Reader::from_path("data/one-shot.df2.gz").unwrap();
+        let shot = reader.read_one().unwrap().unwrap();
+        assert_eq!(1, shot.number);
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn reader_from_path_sniffs_zlib() {
+        let mut reader = Reader::from_path("data/one-shot.df2.zlib").unwrap();
+        let shot = reader.read_one().unwrap().unwrap();
+        assert_eq!(1, shot.number);
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn reader_seek_on_compressed_stream() {
+        let mut reader = Reader::from_path("data/two-shots.df2.gz").unwrap();
+        reader.seek(2).unwrap();
+        let shot = reader.read_one().unwrap().unwrap();
+        assert_eq!(2, shot.number);
+    }
+
+    #[test]
+    fn read_one_bounds_malformed_sample_count() {
+        let mut bytes = Vec::new();
+        bytes.write_u16::<LittleEndian>(1).unwrap(); // number
+        bytes.write_u16::<LittleEndian>(1).unwrap(); // offset: only 2 bytes allowed
+        bytes.write_u16::<LittleEndian>(5).unwrap(); // outgoing nsamples claims 5 samples
+        let mut reader = Reader {
+            reader: &bytes[..],
+            index: None,
+            cache: ShotCache::new(DEFAULT_CACHE_CAPACITY),
+        };
+        match reader.read_one() {
+            Err(Error::InvalidOffset { shot_number, offset }) => {
+                assert_eq!(1, shot_number);
+                assert_eq!(1, offset);
+            }
+            other => panic!("expected an InvalidOffset error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recovering_resyncs_after_invalid_offset() {
+        let mut bytes = Vec::new();
+        // shot 1: offset claims 8 bytes, but the outgoing pulse only needs 6
+        bytes.write_u16::<LittleEndian>(1).unwrap();
+        bytes.write_u16::<LittleEndian>(4).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+        // junk bytes that belong to no shot
+        bytes.extend_from_slice(&[0x99, 0x99]);
+        // shot 2: well-formed
+        bytes.write_u16::<LittleEndian>(2).unwrap();
+        bytes.write_u16::<LittleEndian>(3).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+        bytes.write_u16::<LittleEndian>(7).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+
+        let reader = Reader {
+            reader: &bytes[..],
+            index: None,
+            cache: ShotCache::new(DEFAULT_CACHE_CAPACITY),
+        };
+        let shots = reader.recovering().collect::<Result<Vec<Shot>>>().unwrap();
+        assert_eq!(1, shots.len());
+        assert_eq!(2, shots[0].number);
+    }
+
+    #[test]
+    fn segment_peak() {
+        let segment = Segment {
+            data: vec![1, 5, 3],
+            time_interval: 0,
+        };
+        assert_eq!(Some((1, 5)), segment.peak());
+        let segment = Segment {
+            data: Vec::new(),
+            time_interval: 0,
+        };
+        assert_eq!(None, segment.peak());
+    }
+
+    #[test]
+    fn segment_baseline() {
+        let segment = Segment {
+            data: vec![2, 4],
+            time_interval: 0,
+        };
+        assert_eq!(Some(3.0), segment.baseline());
+        let segment = Segment {
+            data: Vec::new(),
+            time_interval: 0,
+        };
+        assert_eq!(None, segment.baseline());
+    }
+
+    #[test]
+    fn shot_total_samples() {
+        let shot = Shot {
+            number: 1,
+            outgoing: vec![1, 2],
+            segments: vec![Segment {
+                               data: vec![3, 4, 5],
+                               time_interval: 0,
+                           }],
+        };
+        assert_eq!(5, shot.total_samples());
+    }
 }
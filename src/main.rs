@@ -2,10 +2,14 @@ extern crate df2;
 extern crate docopt;
 extern crate rustc_serialize;
 
-use df2::{Reader, Shot};
+use df2::{Index, Reader, Shot};
 use docopt::Docopt;
 use rustc_serialize::json;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 const USAGE: &'static str = "
 Query Optech df2 files.
@@ -35,16 +39,76 @@ fn main() {
     }
 }
 fn print_shot<P: AsRef<Path>>(path: P, number: u16) {
-    let mut reader = Reader::from_path(path).unwrap();
-    reader.seek(number).unwrap();
-    let shot = reader.read_one().unwrap().unwrap();
+    let mut reader = Reader::from_path(&path).unwrap();
+    let index_path = index_path_for(&path).unwrap();
+    let index = Index::load(&index_path).unwrap_or_else(|_| {
+        let index = reader.build_index().unwrap();
+        index.save(&index_path).unwrap();
+        index
+    });
+    let mut reader = reader.with_index(index);
+    let shot = reader.shot(number).unwrap();
     println!("{}", json::as_json(&shot));
 }
 
+/// Returns the path of the sidecar index file for a df2 file.
+///
+/// The df2 file's size and modification time are baked into the sidecar's own file name, so a
+/// stale index left over from a since-edited or since-replaced df2 file simply misses:
+/// `Index::load` fails to find it, `print_shot` rebuilds, and the old sidecar is silently
+/// orphaned rather than trusted.
+fn index_path_for<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    let metadata = fs::metadata(&path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut index_path = path.as_ref().to_path_buf();
+    let file_name = format!("{}.{}-{}.index.json",
+                             index_path.file_name().unwrap().to_string_lossy(),
+                             metadata.len(),
+                             modified);
+    index_path.set_file_name(file_name);
+    Ok(index_path)
+}
+
+#[derive(Debug, RustcEncodable)]
+struct Summary {
+    filename: String,
+    shot_count: usize,
+    segment_count_histogram: BTreeMap<usize, usize>,
+    min_samples_per_shot: usize,
+    max_samples_per_shot: usize,
+    mean_samples_per_shot: f64,
+    total_waveform_bytes: u64,
+}
+
 fn print_summary<P: AsRef<Path>>(path: P) {
-    println!("Filename: {}", path.as_ref().to_string_lossy());
+    let filename = path.as_ref().to_string_lossy().into_owned();
     let shots = Reader::from_path(path)
         .and_then(|reader| reader.collect::<Result<Vec<Shot>, _>>())
         .unwrap();
-    println!("Number of shots: {}", shots.len());
+
+    let mut segment_count_histogram = BTreeMap::new();
+    let mut sample_counts = Vec::new();
+    for shot in &shots {
+        *segment_count_histogram.entry(shot.segments.len()).or_insert(0) += 1;
+        sample_counts.push(shot.total_samples());
+    }
+    let min_samples_per_shot = sample_counts.iter().cloned().min().unwrap_or(0);
+    let max_samples_per_shot = sample_counts.iter().cloned().max().unwrap_or(0);
+    let total_samples: usize = sample_counts.iter().sum();
+    let mean_samples_per_shot = if sample_counts.is_empty() {
+        0.0
+    } else {
+        total_samples as f64 / sample_counts.len() as f64
+    };
+
+    let summary = Summary {
+        filename: filename,
+        shot_count: shots.len(),
+        segment_count_histogram: segment_count_histogram,
+        min_samples_per_shot: min_samples_per_shot,
+        max_samples_per_shot: max_samples_per_shot,
+        mean_samples_per_shot: mean_samples_per_shot,
+        total_waveform_bytes: total_samples as u64 * 2,
+    };
+    println!("{}", json::as_json(&summary));
 }